@@ -1,17 +1,21 @@
+use glob::glob;
 use log::debug;
 use pathdiff::diff_paths;
+use regex::Regex;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::Component;
 use std::path::Path;
+use std::path::PathBuf;
 
-/// Search from cwd backwards to repo_root
-fn back_search(cwd: &Path, repo_root: &Path, file: &str) -> bool {
+/// Search from cwd backwards to repo_root, returning the first matching path
+fn back_search(cwd: &Path, repo_root: &Path, file: &str) -> Option<PathBuf> {
     let mut current_dir = cwd.to_path_buf();
     loop {
         let candidate = current_dir.join(file);
         if candidate.is_file() {
             debug!("Found {}", candidate.display());
-            return true;
+            return Some(candidate);
         }
 
         if current_dir == repo_root {
@@ -20,16 +24,16 @@ fn back_search(cwd: &Path, repo_root: &Path, file: &str) -> bool {
 
         current_dir = current_dir.parent().unwrap().to_path_buf();
     }
-    false
+    None
 }
 
-/// Search from repo_root forward to cwd
-fn forward_search(cwd: &Path, repo_root: &Path, file: &str) -> bool {
+/// Search from repo_root forward to cwd, returning the first matching path
+fn forward_search(cwd: &Path, repo_root: &Path, file: &str) -> Option<PathBuf> {
     if let Some(diff) = diff_paths(cwd, repo_root) {
         let mut path = repo_root.to_path_buf();
         let candidate = path.join(file);
         if candidate.is_file() {
-            return true;
+            return Some(candidate);
         }
 
         for component in diff.components() {
@@ -38,28 +42,119 @@ fn forward_search(cwd: &Path, repo_root: &Path, file: &str) -> bool {
                     path = path.join(s);
                     let candidate = path.join(file);
                     if candidate.is_file() {
-                        return true;
+                        return Some(candidate);
                     }
                 }
                 Component::CurDir => {
                     continue;
                 }
                 _ => {
-                    return false;
+                    return None;
                 }
             }
         }
     }
 
-    false
+    None
 }
-fn path_search(cwd: &Path, repo_root: &Path, direction: &Direction, file: &str) -> bool {
+fn path_search(cwd: &Path, repo_root: &Path, direction: &Direction, file: &str) -> Option<PathBuf> {
     match direction {
         Direction::Backwards => back_search(cwd, repo_root, file),
         Direction::Forwards => forward_search(cwd, repo_root, file),
     }
 }
 
+/// Run a glob pattern in a single directory, returning the first file it yields.
+fn glob_in_dir(dir: &Path, pattern: &str) -> Option<PathBuf> {
+    let joined = dir.join(pattern);
+    if let Ok(paths) = glob(&joined.to_string_lossy()) {
+        for entry in paths.flatten() {
+            if entry.is_file() {
+                debug!("Glob {} matched {}", pattern, entry.display());
+                return Some(entry);
+            }
+        }
+    }
+    None
+}
+
+/// Glob from cwd backwards to repo_root, returning the first matching file
+fn back_glob_search(cwd: &Path, repo_root: &Path, pattern: &str) -> Option<PathBuf> {
+    let mut current_dir = cwd.to_path_buf();
+    loop {
+        if let Some(found) = glob_in_dir(&current_dir, pattern) {
+            return Some(found);
+        }
+
+        if current_dir == repo_root {
+            break;
+        }
+
+        current_dir = current_dir.parent().unwrap().to_path_buf();
+    }
+    None
+}
+
+/// Glob from repo_root forward to cwd, returning the first matching file
+fn forward_glob_search(cwd: &Path, repo_root: &Path, pattern: &str) -> Option<PathBuf> {
+    if let Some(diff) = diff_paths(cwd, repo_root) {
+        if let Some(found) = glob_in_dir(repo_root, pattern) {
+            return Some(found);
+        }
+
+        let mut path = repo_root.to_path_buf();
+        for component in diff.components() {
+            match component {
+                Component::Normal(s) => {
+                    path = path.join(s);
+                    if let Some(found) = glob_in_dir(&path, pattern) {
+                        return Some(found);
+                    }
+                }
+                Component::CurDir => {
+                    continue;
+                }
+                _ => {
+                    return None;
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn glob_search(
+    cwd: &Path,
+    repo_root: &Path,
+    direction: &Direction,
+    pattern: &str,
+) -> Option<PathBuf> {
+    match direction {
+        Direction::Backwards => back_glob_search(cwd, repo_root, pattern),
+        Direction::Forwards => forward_glob_search(cwd, repo_root, pattern),
+    }
+}
+
+/// Test a file's contents for a literal `contains` substring.
+fn content_contains(path: &Path, needle: &str) -> bool {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return false;
+    };
+    contents.contains(needle)
+}
+
+/// Test a file's contents against a `contains-regex` pattern.
+fn content_matches_regex(path: &Path, pattern: &str) -> bool {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return false;
+    };
+    match Regex::new(pattern) {
+        Ok(re) => re.is_match(&contents),
+        Err(_) => false,
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 pub enum StringOrVec {
@@ -76,15 +171,41 @@ impl StringOrVec {
     }
 }
 
+/// A single named task: the command(s) to run plus the tasks that must run first.
+///
+/// Accepts either a bare command list (the shape the builtin verbs have always
+/// used) or a table with an explicit `needs` prerequisite list.
 #[derive(Debug, Deserialize)]
-pub struct Tools {
-    pub format: Option<StringOrVec>,
-    pub check: Option<StringOrVec>,
-    pub build: Option<StringOrVec>,
-    pub test: Option<StringOrVec>,
-    pub run: Option<StringOrVec>,
+#[serde(untagged)]
+pub enum TaskDef {
+    Commands(StringOrVec),
+    Task {
+        commands: StringOrVec,
+        #[serde(default)]
+        needs: Vec<String>,
+    },
+}
+
+impl TaskDef {
+    pub fn commands(&self) -> Vec<String> {
+        match self {
+            TaskDef::Commands(commands) => commands.to_vec(),
+            TaskDef::Task { commands, .. } => commands.to_vec(),
+        }
+    }
+
+    pub fn needs(&self) -> &[String] {
+        match self {
+            TaskDef::Commands(_) => &[],
+            TaskDef::Task { needs, .. } => needs,
+        }
+    }
 }
 
+/// Tasks keyed by name. The five builtin verbs (`format`, `check`, `build`,
+/// `test`, `run`) are simply the default task names; any other name works too.
+pub type Tools = HashMap<String, TaskDef>;
+
 #[derive(Debug, Deserialize, Default)]
 pub enum Direction {
     #[serde(rename = "backwards")]
@@ -96,11 +217,35 @@ pub enum Direction {
 
 #[derive(Debug, Deserialize)]
 pub struct File {
-    pub(crate) name: StringOrVec,
+    pub(crate) name: Option<StringOrVec>,
+    /// Glob pattern(s) that must match at least one file (e.g. `*.rs`)
+    pub(crate) glob: Option<StringOrVec>,
+    /// Literal substring the matched file's contents must contain
+    pub(crate) contains: Option<String>,
+    /// Regex the matched file's contents must match (opt-in alternative to `contains`)
+    #[serde(rename = "contains-regex")]
+    pub(crate) contains_regex: Option<String>,
     #[serde(rename = "search-direction", default)]
     search_direction: Direction,
 }
 
+impl File {
+    /// Check a matched file against the configured content predicates.
+    fn content_ok(&self, path: &Path) -> bool {
+        if let Some(needle) = &self.contains {
+            if !content_contains(path, needle) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.contains_regex {
+            if !content_matches_regex(path, pattern) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Entry {
     pub(crate) name: String,
@@ -122,9 +267,26 @@ impl Entry {
 
         if let Some(file) = &self.file {
             let direction = &file.search_direction;
-            for file in file.name.to_vec() {
-                if !path_search(cwd, repo_root, direction, &file) {
-                    return false;
+
+            if let Some(names) = &file.name {
+                for name in names.to_vec() {
+                    let Some(found) = path_search(cwd, repo_root, direction, &name) else {
+                        return false;
+                    };
+                    if !file.content_ok(&found) {
+                        return false;
+                    }
+                }
+            }
+
+            if let Some(globs) = &file.glob {
+                for pattern in globs.to_vec() {
+                    let Some(found) = glob_search(cwd, repo_root, direction, &pattern) else {
+                        return false;
+                    };
+                    if !file.content_ok(&found) {
+                        return false;
+                    }
                 }
             }
         }
@@ -133,14 +295,25 @@ impl Entry {
     }
 }
 
+/// Top-level settings, distinct from the per-target `entry` rules.
+#[derive(Debug, Deserialize, Default)]
+pub struct Settings {
+    /// Sentinel file names the marker VCS backend ascends to look for
+    #[serde(rename = "root-markers", default)]
+    pub(crate) root_markers: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub(crate) settings: Settings,
     #[serde(rename = "entry")]
     pub(crate) entries: Vec<Entry>,
 }
 
 impl Config {
     pub fn append(&mut self, mut other: Config) {
+        self.settings.root_markers.append(&mut other.settings.root_markers);
         self.entries.append(&mut other.entries);
     }
 }
@@ -8,6 +8,7 @@ use log::info;
 use log::Level;
 use log::{error, warn};
 use owo_colors::OwoColorize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::env;
 use std::fmt;
 use std::fmt::Display;
@@ -15,18 +16,23 @@ use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 use thiserror::Error;
 use tokio_stream::StreamExt;
 use xcommand::StdioType;
 use xcommand::XCommand;
 use xcommand::XStatus;
 mod config;
+mod vcs;
 use config::Config;
+use config::Tools;
+use vcs::{Git, Jujutsu, Marker, Mercurial, RootBackend};
 
 // TODO: make indent configurable
 const INDENT: &str = "   ";
-const USER_CONFIG_NAME: &str = "config.toml";
 const REPO_CONFIG_NAME: &str = ".cbtr.toml"; // TODO: make configurable
+const ROOT_MARKER: &str = ".cbtr-root"; // default when config sets no root-markers
 
 #[derive(Debug, Args, Clone)]
 struct CommandArgs {
@@ -37,6 +43,18 @@ struct CommandArgs {
     /// Only search CWD for file rules (do not search between CWD and repo root)
     #[arg(short, long)]
     no_searchback: bool,
+
+    /// Number of tool subprocesses to run concurrently within a task
+    #[arg(short, long, default_value_t = 1)]
+    jobs: usize,
+
+    /// Keep running remaining tools after a failure, then report all failures
+    #[arg(short, long)]
+    keep_going: bool,
+
+    /// Extra arguments (after `--`) appended to every command in the matched tool list
+    #[arg(last = true)]
+    extra: Vec<String>,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -98,8 +116,10 @@ enum Multicall {
     #[command(flatten)]
     Multicall(Command),
     Cbtr {
-        #[command(subcommand)]
-        command: Command,
+        /// Task to run (a builtin verb or a user-defined task name)
+        task: String,
+        #[clap(flatten)]
+        args: CommandArgs,
     },
 }
 
@@ -114,16 +134,27 @@ struct Cli {
     multicall: Multicall,
 }
 
-fn repo_root(cwd: &Path) -> Result<PathBuf> {
-    let repo = gix::discover(cwd)?;
-    let git_dir = repo.path();
-    let root = git_dir.parent().unwrap();
-    Ok(root.to_path_buf())
+fn repo_root(cwd: &Path, markers: &[String]) -> Result<PathBuf> {
+    // Try each enabled backend in order and use the first match, so cbtr works
+    // in non-git monorepos and VCS-agnostic workspaces.
+    let backends: Vec<Box<dyn RootBackend>> = vec![
+        Box::new(Git),
+        Box::new(Jujutsu),
+        Box::new(Mercurial),
+        Box::new(Marker::new(markers.to_vec())),
+    ];
+    for backend in &backends {
+        if let Some(root) = backend.discover(cwd) {
+            return Ok(root);
+        }
+    }
+    bail!("Could not determine a workspace root for {}", cwd.display());
 }
 
-async fn run(cmd: &str, args: &[&str]) -> Result<i32> {
-    let bin = which::which(cmd)?;
-    let command = XCommand::builder(&bin)?.args(args)?.build();
+async fn run(argv: &[String]) -> Result<i32> {
+    let bin = which::which(&argv[0])?;
+    let args: Vec<&str> = argv[1..].iter().map(|s| s.as_str()).collect();
+    let command = XCommand::builder(&bin)?.args(&args)?.build();
     let Ok(mut child) = command.spawn() else {
         bail!("Unable to run '{}'", bin.display());
     };
@@ -150,24 +181,107 @@ async fn run(cmd: &str, args: &[&str]) -> Result<i32> {
     Ok(code)
 }
 
+/// Run a tool under parallelism, buffering its output and flushing it as a
+/// labeled block once the child exits so lines from different processes don't
+/// interleave unreadably.
+async fn run_buffered(argv: Vec<String>) -> Result<(String, i32)> {
+    let label = argv.join(" ");
+    let bin = which::which(&argv[0])?;
+    let args: Vec<&str> = argv[1..].iter().map(|s| s.as_str()).collect();
+    let command = XCommand::builder(&bin)?.args(&args)?.build();
+    let Ok(mut child) = command.spawn() else {
+        bail!("Unable to run '{}'", bin.display());
+    };
+
+    // Collect the child's output rather than printing it live.
+    let mut buffer: Vec<(StdioType, String)> = Vec::new();
+    let mut streamer = child.streamer();
+    let mut stream = streamer.stream();
+    while let Some(item) = stream.next().await {
+        buffer.push(item?);
+    }
+
+    let XStatus::Exited(code) = child.status().await? else {
+        bail!("Process was expected to have finished");
+    };
+
+    // Flush the whole block at once, labeled with the tool it came from.
+    println!("{}", format!("[{}]", label).bold());
+    for (message_type, message) in buffer {
+        match message_type {
+            StdioType::Stdout => println!("{}{}", INDENT, message),
+            StdioType::Stderr => eprintln!("{}{}", INDENT, message),
+        }
+    }
+    Ok((label, code))
+}
+
 fn user_config() -> Result<Config> {
     let Some(proj_dirs) = ProjectDirs::from("", "", "cbtr") else {
         bail!("Couldn't find proj dirs");
     };
 
-    // TODO: config dir should contain multiple tomls, where each toml could share the same 'entry.file' or 'entry.bin'
+    // Every `*.toml` in the config dir contributes entries, so a user can split
+    // their tool rules across several files (e.g. `rust.toml`, `python.toml`).
     let config_dir = proj_dirs.config_dir();
     if !config_dir.is_dir() {
         fs::create_dir_all(config_dir)?;
     }
 
-    let config_file = config_dir.join(USER_CONFIG_NAME);
-    if !config_file.is_file() {
-        bail!("Please create a cbtr config at {}", config_file.display());
+    // Collect and sort for a stable merge order across platforms.
+    let mut toml_files: Vec<PathBuf> = fs::read_dir(config_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    toml_files.sort();
+
+    if toml_files.is_empty() {
+        bail!("Please create a cbtr config at {}", config_dir.display());
+    }
+
+    let mut config: Option<Config> = None;
+    for toml_file in toml_files {
+        debug!("Reading user config {}", toml_file.display());
+        let contents = fs::read_to_string(&toml_file)?;
+        let parsed: Config = toml::from_str(&contents)?;
+        match &mut config {
+            Some(config) => config.append(parsed),
+            None => config = Some(parsed),
+        }
+    }
+
+    let Some(config) = config else {
+        bail!("Please create a cbtr config at {}", config_dir.display());
     };
+    Ok(config)
+}
+
+/// Collect every `.cbtr.toml` between `cwd` and `repo_root`, nearest-to-cwd first
+/// so those entries win in match order.
+fn repo_configs(cwd: &Path, repo_root: &Path) -> Result<Option<Config>> {
+    let mut config: Option<Config> = None;
+    let mut current_dir = cwd.to_path_buf();
+    loop {
+        let repo_config_file = current_dir.join(REPO_CONFIG_NAME);
+        if repo_config_file.is_file() {
+            debug!("Reading repo config {}", repo_config_file.display());
+            let contents = fs::read_to_string(&repo_config_file)?;
+            let parsed: Config = toml::from_str(&contents)?;
+            match &mut config {
+                Some(config) => config.append(parsed),
+                None => config = Some(parsed),
+            }
+        }
 
-    let contents = fs::read_to_string(&config_file)?;
-    let config: Config = toml::from_str(&contents)?;
+        if current_dir == repo_root {
+            break;
+        }
+
+        let Some(parent) = current_dir.parent() else {
+            break;
+        };
+        current_dir = parent.to_path_buf();
+    }
     Ok(config)
 }
 
@@ -191,20 +305,33 @@ async fn main() -> Result<()> {
         })
         .init();
 
-    let args = Cli::parse();
-    let command = match &args.multicall {
-        Multicall::Multicall(c) => c,
-        Multicall::Cbtr { command } => command,
+    let cli = Cli::parse();
+    debug!("args: {:?}", cli);
+    let (task_name, args): (String, &CommandArgs) = match &cli.multicall {
+        Multicall::Multicall(c) => (c.to_string(), c.args()),
+        Multicall::Cbtr { task, args } => (task.clone(), args),
     };
-    debug!("args: {:?}", args);
-    let args = command.args();
 
     let cwd = env::current_dir()?;
+
+    // Load the user config first so the marker backend can source its sentinel
+    // names from it before we walk for the workspace root.
+    let user_config = match user_config() {
+        Ok(config) => Some(config),
+        Err(_) => None,
+    };
+    let markers: Vec<String> = match &user_config {
+        Some(config) if !config.settings.root_markers.is_empty() => {
+            config.settings.root_markers.clone()
+        }
+        _ => vec![ROOT_MARKER.to_string()],
+    };
+
     let root = if args.no_searchback {
         // Stop searchback by making repo_root == cwd
         cwd.clone()
     } else {
-        match repo_root(&cwd) {
+        match repo_root(&cwd, &markers) {
             Ok(root) => root,
             Err(_) => {
                 // Fall back to cwd if we aren't working in a git repo
@@ -214,19 +341,7 @@ async fn main() -> Result<()> {
         }
     };
 
-    let repo_config_file = root.join(REPO_CONFIG_NAME);
-    let repo_config = if repo_config_file.is_file() {
-        let contents = fs::read_to_string(&repo_config_file)?;
-        let config: Config = toml::from_str(&contents)?;
-        Some(config)
-    } else {
-        None
-    };
-
-    let user_config = match user_config() {
-        Ok(config) => Some(config),
-        Err(_) => None,
-    };
+    let repo_config = repo_configs(&cwd, &root)?;
 
     let config = match (repo_config, user_config) {
         (Some(mut repo_config), Some(user_config)) => {
@@ -242,58 +357,191 @@ async fn main() -> Result<()> {
         }
     };
 
-    let mut tools = None;
+    let mut tasks = None;
     for entry in &config.entries {
         let name = &entry.name;
         debug!("Checking conditions for {}", name);
 
-        if entry.matches(&cwd, &root) {
-            match command {
-                Command::Format { args: _ } => {
-                    tools = entry.tools.format.as_ref();
-                }
-                Command::Check { args: _ } => {
-                    tools = entry.tools.check.as_ref();
-                }
-                Command::Build { args: _ } => {
-                    tools = entry.tools.build.as_ref();
-                }
-                Command::Test { args: _ } => {
-                    tools = entry.tools.test.as_ref();
-                }
-                Command::Run { args: _ } => {
-                    tools = entry.tools.run.as_ref();
-                }
-            }
-
-            if tools.is_some() {
-                break;
-            }
+        if entry.matches(&cwd, &root) && entry.tools.contains_key(&task_name) {
+            tasks = Some(&entry.tools);
+            break;
         }
     }
 
-    let Some(tools) = tools else {
-        error!("No {} tool matched config rules", command);
+    let Some(tasks) = tasks else {
+        error!("No '{}' task matched config rules", task_name);
         std::process::exit(1);
     };
 
-    for tool in tools.to_vec() {
-        info!("Running '{}'", tool.bold());
+    // Resolve the requested task and its prerequisites into execution order.
+    let order = match task_order(tasks, &task_name) {
+        Ok(order) => order,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Collected (tool, code) pairs when running under --keep-going.
+    let mut failures: Vec<(String, i32)> = Vec::new();
+    // Tasks whose commands failed, and tasks skipped because a prerequisite did
+    // not succeed. Because `order` is topological, checking direct `needs`
+    // against these sets propagates a failure down the whole dependency chain.
+    let mut failed_tasks: BTreeSet<String> = BTreeSet::new();
+    let mut skipped_tasks: BTreeSet<String> = BTreeSet::new();
+
+    for task in order {
+        // Don't run a task whose prerequisite failed (or was itself skipped).
+        let unmet: Vec<&String> = tasks[&task]
+            .needs()
+            .iter()
+            .filter(|need| failed_tasks.contains(*need) || skipped_tasks.contains(*need))
+            .collect();
+        if !unmet.is_empty() {
+            warn!("Skipping task '{}': prerequisite(s) {:?} did not succeed", task, unmet);
+            skipped_tasks.insert(task.clone());
+            continue;
+        }
+
+        // Build each command as a real argv and append the `-- <args>`
+        // pass-through as discrete entries, so an extra argument with embedded
+        // whitespace survives intact rather than being re-split downstream.
+        let commands: Vec<Vec<String>> = tasks[&task]
+            .commands()
+            .into_iter()
+            .map(|tool| {
+                let mut argv: Vec<String> =
+                    tool.split_whitespace().map(|s| s.to_owned()).collect();
+                argv.extend(args.extra.iter().cloned());
+                argv
+            })
+            .collect();
 
         if args.dry_run {
-            println!("[dryrun] Would run '{}'", tool)
+            for argv in &commands {
+                let tool = argv.join(" ");
+                info!("Running '{}'", tool.bold());
+                println!("[dryrun] Would run '{}'", tool)
+            }
+            continue;
+        }
+
+        let mut task_failed = false;
+
+        if args.jobs <= 1 {
+            // Sequential, live-streamed output (the default).
+            for argv in &commands {
+                let tool = argv.join(" ");
+                info!("Running '{}'", tool.bold());
+                let code = run(argv).await?;
+                debug!("argv: {:?}", argv);
+                if code != 0 {
+                    error!("Subprocess '{}' failed with exit code {}", tool, code);
+                    if args.keep_going {
+                        failures.push((tool, code));
+                        task_failed = true;
+                    } else {
+                        std::process::exit(code)
+                    }
+                };
+            }
         } else {
-            let parts: Vec<&str> = tool.split_whitespace().collect();
-            let cmd = parts[0];
-            let cmd_args = &parts[1..];
-            let code = run(cmd, cmd_args).await?;
-            debug!("cmd: {}, args: {:?}", cmd, cmd_args);
-            if code != 0 {
-                error!("Subprocess '{}' failed with exit code {}", tool, code);
-                std::process::exit(code)
-            };
+            // Run up to `jobs` tools at once, bounded by a semaphore.
+            let semaphore = Arc::new(Semaphore::new(args.jobs));
+            let mut handles = Vec::new();
+            for argv in commands {
+                info!("Running '{}'", argv.join(" ").bold());
+                let semaphore = Arc::clone(&semaphore);
+                handles.push(tokio::spawn(async move {
+                    let _permit = semaphore.acquire().await?;
+                    run_buffered(argv).await
+                }));
+            }
+            for handle in handles {
+                let (tool, code) = handle.await??;
+                if code != 0 {
+                    error!("Subprocess '{}' failed with exit code {}", tool, code);
+                    if args.keep_going {
+                        failures.push((tool, code));
+                        task_failed = true;
+                    } else {
+                        std::process::exit(code)
+                    }
+                };
+            }
+        }
+
+        if task_failed {
+            failed_tasks.insert(task.clone());
         }
     }
 
+    if !failures.is_empty() {
+        error!("{} tool(s) failed:", failures.len());
+        for (tool, code) in &failures {
+            error!("  '{}' exited with code {}", tool, code);
+        }
+        for task in &skipped_tasks {
+            error!("  task '{}' skipped (prerequisite failed)", task);
+        }
+        // Exit with the code of the first failure.
+        std::process::exit(failures[0].1);
+    }
+
     Ok(())
 }
+
+/// Topologically sort the requested task and its transitive `needs` via Kahn's
+/// algorithm so every prerequisite runs before the task that depends on it.
+fn task_order(tasks: &Tools, target: &str) -> Result<Vec<String>> {
+    // Gather the subgraph reachable from `target` through its `needs` edges.
+    let mut subgraph: BTreeSet<String> = BTreeSet::new();
+    let mut stack = vec![target.to_string()];
+    while let Some(name) = stack.pop() {
+        let Some(def) = tasks.get(&name) else {
+            bail!("Task '{}' is not defined", name);
+        };
+        if subgraph.insert(name) {
+            stack.extend(def.needs().iter().cloned());
+        }
+    }
+
+    // Seed the queue with zero-in-degree nodes (tasks with no prerequisites).
+    // Count distinct prerequisites so a duplicated `needs` entry (e.g.
+    // `needs = ["build", "build"]`) doesn't inflate the in-degree and masquerade
+    // as an unsatisfiable cycle.
+    let mut in_degree: BTreeMap<String, usize> = subgraph
+        .iter()
+        .map(|name| {
+            let distinct: BTreeSet<&String> = tasks[name].needs().iter().collect();
+            (name.clone(), distinct.len())
+        })
+        .collect();
+    let mut queue: VecDeque<String> = in_degree
+        .iter()
+        .filter(|(_, degree)| **degree == 0)
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    let mut order = Vec::with_capacity(subgraph.len());
+    while let Some(name) = queue.pop_front() {
+        // Decrement successors: any task that lists `name` as a prerequisite.
+        for other in &subgraph {
+            if tasks[other].needs().iter().any(|need| need == &name) {
+                let degree = in_degree.get_mut(other).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    queue.push_back(other.clone());
+                }
+            }
+        }
+        order.push(name);
+    }
+
+    if order.len() != subgraph.len() {
+        let remaining: Vec<&String> = subgraph.iter().filter(|n| !order.contains(n)).collect();
+        bail!("Dependency cycle among tasks: {:?}", remaining);
+    }
+
+    Ok(order)
+}
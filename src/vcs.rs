@@ -0,0 +1,71 @@
+use log::debug;
+use std::path::{Path, PathBuf};
+
+/// A strategy for locating the root of a workspace from some directory inside it.
+///
+/// Third parties can add support for their own VCS (or workspace layout) by
+/// implementing this trait and slotting the backend into the list `main` walks.
+pub trait RootBackend {
+    fn discover(&self, cwd: &Path) -> Option<PathBuf>;
+}
+
+/// Ascend from `cwd` until a directory containing `marker` is found.
+fn ascend_for(cwd: &Path, marker: &str) -> Option<PathBuf> {
+    let mut dir = cwd.to_path_buf();
+    loop {
+        if dir.join(marker).exists() {
+            return Some(dir);
+        }
+        dir = dir.parent()?.to_path_buf();
+    }
+}
+
+/// Git repositories, discovered via gix.
+pub struct Git;
+impl RootBackend for Git {
+    fn discover(&self, cwd: &Path) -> Option<PathBuf> {
+        let repo = gix::discover(cwd).ok()?;
+        let git_dir = repo.path();
+        Some(git_dir.parent()?.to_path_buf())
+    }
+}
+
+/// Jujutsu workspaces, marked by a `.jj` directory.
+pub struct Jujutsu;
+impl RootBackend for Jujutsu {
+    fn discover(&self, cwd: &Path) -> Option<PathBuf> {
+        ascend_for(cwd, ".jj")
+    }
+}
+
+/// Mercurial repositories, marked by a `.hg` directory.
+pub struct Mercurial;
+impl RootBackend for Mercurial {
+    fn discover(&self, cwd: &Path) -> Option<PathBuf> {
+        ascend_for(cwd, ".hg")
+    }
+}
+
+/// Generic backend that ascends until it finds one of a set of sentinel files,
+/// for VCS-agnostic monorepos (e.g. a `.cbtr-root` marker).
+pub struct Marker {
+    markers: Vec<String>,
+}
+
+impl Marker {
+    pub fn new(markers: Vec<String>) -> Self {
+        Marker { markers }
+    }
+}
+
+impl RootBackend for Marker {
+    fn discover(&self, cwd: &Path) -> Option<PathBuf> {
+        for marker in &self.markers {
+            if let Some(root) = ascend_for(cwd, marker) {
+                debug!("Found root marker {}", marker);
+                return Some(root);
+            }
+        }
+        None
+    }
+}